@@ -7,6 +7,24 @@ enum OperatorKind {
     Sub,
     Mul,
     Div,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+/***
+ * 単項演算子を表現する列挙体
+ */
+#[derive(Debug, Clone)]
+enum UnaryKind {
+    Neg,
 }
 /***
  * 式（Expression）を定義・表現する列挙体
@@ -14,8 +32,8 @@ enum OperatorKind {
 #[derive(Debug, Clone)]
 enum Expr {
     Operation(Box<BinaryOp>),
+    Unary { op: UnaryKind, operand: Box<Expr> },
     Number(Option<Number>),
-    Operator(OperatorKind),
 }
 /***
  * 2項演算を表現する構造体
@@ -36,13 +54,23 @@ impl BinaryOp {
 }
 
 /***
- * 数値を表現する構造体
+ * 数値を表現する列挙体（数値タワー）
+ * Integer: 整数
+ * Floating: 浮動小数点数
+ * 加減乗除はどちらか一方でも Floating なら結果を Floating へ昇格する。
+ * ビット演算・シフトは両辺を整数へ切り捨てて行い、結果は常に Integer。
  */
-#[derive(Debug, Clone)]
-struct Number(u128);
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Integer(i128),
+    Floating(f64),
+}
 impl Number {
-    fn new(n: u128) -> Expr {
-        Expr::Number(Some(Number(n)))
+    fn new(n: i128) -> Expr {
+        Expr::Number(Some(Number::Integer(n)))
+    }
+    fn floating(f: f64) -> Expr {
+        Expr::Number(Some(Number::Floating(f)))
     }
     fn from_expr(expr: Expr) -> Option<Number> {
         match expr {
@@ -50,35 +78,178 @@ impl Number {
             _ => None,
         }
     }
+    // 浮動小数点として取り出す（昇格・比較用）。
+    fn as_f64(&self) -> f64 {
+        match self {
+            Number::Integer(n) => *n as f64,
+            Number::Floating(f) => *f,
+        }
+    }
+    // 整数として取り出す（ビット演算用。浮動小数は切り捨てる）。
+    fn as_i128(&self) -> i128 {
+        match self {
+            Number::Integer(n) => *n,
+            Number::Floating(f) => *f as i128,
+        }
+    }
+    // 比較用の順序。整数同士は i128 で厳密に比べ、片方でも浮動小数のときだけ
+    // f64 に昇格する（NaN が絡むと None）。
+    fn cmp_with(&self, other: &Number) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => Some(a.cmp(b)),
+            _ => self.as_f64().partial_cmp(&other.as_f64()),
+        }
+    }
 }
-impl std::ops::Sub for Number {
-    type Output = Expr;
-    fn sub(self, other: Self) -> Self::Output {
-        Number::new(self.0 - other.0)
+// 整数演算のオーバーフロー方向からエラー種別を選ぶ。
+fn overflow_error(underflow: bool) -> ArithmeticError {
+    ArithmeticError::new(if underflow {
+        ArithmeticErrorKind::Underflow
+    } else {
+        ArithmeticErrorKind::Overflow
+    })
+}
+// 加減乗算はチェック付きで行い、整数が桁あふれしたら panic せずエラーを返す。
+// 浮動小数側は inf/NaN へ飽和するので検査しない。
+impl Number {
+    fn checked_add(self, other: Self) -> Result<Expr, ArithmeticError> {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => match a.checked_add(b) {
+                Some(v) => Ok(Number::new(v)),
+                None => Err(overflow_error(a < 0)),
+            },
+            (a, b) => Ok(Number::floating(a.as_f64() + b.as_f64())),
+        }
+    }
+    fn checked_sub(self, other: Self) -> Result<Expr, ArithmeticError> {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => match a.checked_sub(b) {
+                Some(v) => Ok(Number::new(v)),
+                None => Err(overflow_error(a < 0)),
+            },
+            (a, b) => Ok(Number::floating(a.as_f64() - b.as_f64())),
+        }
+    }
+    fn checked_mul(self, other: Self) -> Result<Expr, ArithmeticError> {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => match a.checked_mul(b) {
+                Some(v) => Ok(Number::new(v)),
+                None => Err(overflow_error((a < 0) ^ (b < 0))),
+            },
+            (a, b) => Ok(Number::floating(a.as_f64() * b.as_f64())),
+        }
+    }
+}
+// 除算もチェック付きで行う。整数の零除数は DivByZero、i128::MIN / -1 のような
+// 桁あふれは Overflow にし、panic させない。
+impl Number {
+    fn checked_div(self, other: Self) -> Result<Expr, ArithmeticError> {
+        match (self, other) {
+            // 整数同士は割り切れれば整数、そうでなければ浮動小数へ昇格する。
+            (Number::Integer(a), Number::Integer(b)) => {
+                if b == 0 {
+                    return Err(ArithmeticError::new(ArithmeticErrorKind::DivByZero));
+                }
+                match a.checked_rem(b) {
+                    Some(0) => match a.checked_div(b) {
+                        Some(v) => Ok(Number::new(v)),
+                        None => Err(ArithmeticError::new(ArithmeticErrorKind::Overflow)),
+                    },
+                    Some(_) => Ok(Number::floating(a as f64 / b as f64)),
+                    None => Err(ArithmeticError::new(ArithmeticErrorKind::Overflow)),
+                }
+            }
+            (a, b) => Ok(Number::floating(a.as_f64() / b.as_f64())),
+        }
+    }
+}
+
+// 単項マイナスもチェック付きで行う。i128::MIN の符号反転は panic するため
+// オーバーフロー扱いにする。
+impl Number {
+    fn checked_neg(self) -> Result<Expr, ArithmeticError> {
+        match self {
+            Number::Integer(n) => match n.checked_neg() {
+                Some(v) => Ok(Number::new(v)),
+                None => Err(ArithmeticError::new(ArithmeticErrorKind::Overflow)),
+            },
+            Number::Floating(f) => Ok(Number::floating(-f)),
+        }
     }
 }
-impl std::ops::Add for Number {
+impl std::ops::BitAnd for Number {
     type Output = Expr;
-    fn add(self, other: Self) -> Self::Output {
-        Number::new(self.0 + other.0)
+    fn bitand(self, other: Self) -> Self::Output {
+        Number::new(self.as_i128() & other.as_i128())
     }
 }
-impl std::ops::Mul for Number {
+impl std::ops::BitOr for Number {
     type Output = Expr;
-    fn mul(self, other: Self) -> Self::Output {
-        Number::new(self.0 * other.0)
+    fn bitor(self, other: Self) -> Self::Output {
+        Number::new(self.as_i128() | other.as_i128())
     }
 }
-impl std::ops::Div for Number {
+impl std::ops::BitXor for Number {
     type Output = Expr;
-    fn div(self, other: Self) -> Self::Output {
-        Number::new(self.0 / other.0)
+    fn bitxor(self, other: Self) -> Self::Output {
+        Number::new(self.as_i128() ^ other.as_i128())
+    }
+}
+// シフトはチェック付きで行う。シフト量が 0..128 の範囲外（負数や桁数以上）なら
+// panic せずオーバーフロー扱いにする。
+impl Number {
+    fn checked_shl(self, other: Self) -> Result<Expr, ArithmeticError> {
+        let rhs = other.as_i128();
+        if !(0..i128::BITS as i128).contains(&rhs) {
+            return Err(ArithmeticError::new(ArithmeticErrorKind::Overflow));
+        }
+        Ok(Number::new(self.as_i128() << rhs as u32))
+    }
+    fn checked_shr(self, other: Self) -> Result<Expr, ArithmeticError> {
+        let rhs = other.as_i128();
+        if !(0..i128::BITS as i128).contains(&rhs) {
+            return Err(ArithmeticError::new(ArithmeticErrorKind::Overflow));
+        }
+        Ok(Number::new(self.as_i128() >> rhs as u32))
     }
 }
 
-impl std::cmp::PartialEq<u128> for Number {
-    fn eq(&self, rhs: &u128) -> bool {
-        self.0 == *rhs
+// 整数値との比較（Value 経由でテストの結果照合に使う）。浮動小数はどの整数とも
+// 等しいとはみなさない。零除数の検出は checked_div が i128 のまま行う。
+impl std::cmp::PartialEq<i128> for Number {
+    fn eq(&self, rhs: &i128) -> bool {
+        match self {
+            Number::Integer(n) => n == rhs,
+            Number::Floating(_) => false,
+        }
+    }
+}
+
+/***
+ * 評価結果を表現する列挙体
+ * Number: 数値
+ * Bool: 比較演算などが返す真偽値
+ */
+#[derive(Debug, Clone)]
+enum Value {
+    Number(Number),
+    Bool(bool),
+}
+impl Value {
+    // 数値を要求する文脈で取り出す。真偽値が来たら型エラー。
+    fn into_number(self) -> Result<Number, ArithmeticError> {
+        match self {
+            Value::Number(n) => Ok(n),
+            Value::Bool(_) => Err(ArithmeticError::new(ArithmeticErrorKind::TypeMismatch)),
+        }
+    }
+}
+impl std::cmp::PartialEq<i128> for Value {
+    fn eq(&self, rhs: &i128) -> bool {
+        match self {
+            Value::Number(n) => n == rhs,
+            Value::Bool(_) => false,
+        }
     }
 }
 
@@ -90,6 +261,9 @@ impl std::cmp::PartialEq<u128> for Number {
 enum ArithmeticErrorKind {
     Success,
     DivByZero,
+    TypeMismatch,
+    Overflow,
+    Underflow,
 }
 
 #[derive(Debug)]
@@ -103,11 +277,18 @@ impl ArithmeticError {
         }
     }
     fn to_enum(&self) -> Option<ArithmeticErrorKind> {
-        let success = ArithmeticErrorKind::Success as usize;
-        let div_by_zero = ArithmeticErrorKind::DivByZero as usize;
         match self.e_code {
-            success => Some(ArithmeticErrorKind::Success),
-            div_by_zero => Some(ArithmeticErrorKind::DivByZero),
+            x if x == ArithmeticErrorKind::Success as usize => Some(ArithmeticErrorKind::Success),
+            x if x == ArithmeticErrorKind::DivByZero as usize => {
+                Some(ArithmeticErrorKind::DivByZero)
+            }
+            x if x == ArithmeticErrorKind::TypeMismatch as usize => {
+                Some(ArithmeticErrorKind::TypeMismatch)
+            }
+            x if x == ArithmeticErrorKind::Overflow as usize => Some(ArithmeticErrorKind::Overflow),
+            x if x == ArithmeticErrorKind::Underflow as usize => {
+                Some(ArithmeticErrorKind::Underflow)
+            }
             _ => None,
         }
     }
@@ -116,6 +297,9 @@ impl ArithmeticError {
         match self.to_enum() {
             Some(ArithmeticErrorKind::Success) => "成功しました。",
             Some(ArithmeticErrorKind::DivByZero) => "解無し：ゼロ除算が発生しました。",
+            Some(ArithmeticErrorKind::TypeMismatch) => "型エラー：数値が必要な箇所に真偽値が渡されました。",
+            Some(ArithmeticErrorKind::Overflow) => "算術エラー：計算結果が上限を超えました（オーバーフロー）。",
+            Some(ArithmeticErrorKind::Underflow) => "算術エラー：計算結果が下限を下回りました（アンダーフロー）。",
             None => "存在しないエラーコードが指定されました。",
         }
         .to_owned()
@@ -129,73 +313,568 @@ impl std::fmt::Display for ArithmeticError {
 impl std::error::Error for ArithmeticError {}
 
 /***
- * Ok(Number):計算結果
- * Err(ArithmeticError):解無し(0除算など数学的に解が出ないもの)
- */
-fn parser(op: Expr) -> Result<Number, ArithmeticError> {
-    // 普通の式
-    let mut parser_stack = Vec::new();
-    parser_stack.push(op);
-    // 式を分解して、逆ポーランド記法的な感じでスタックに突っ込んでいく。
-    // 逆ポーランド記法で入れていくスタック
-    let mut rev_polish = Vec::new();
-    loop {
-        let v = parser_stack.pop();
-        match v.unwrap() {
-            Expr::Number(num) => {
-                // スタックが空になったらパースを終了する。
-                rev_polish.push(Expr::Number(num));
-                if parser_stack.len() == 0 {
-                    break;
+ * スタックVMが実行する命令
+ * Push: 定数をスタックへ積む
+ * それ以外: スタックからオペランドを取り出して演算し、結果を積み直す
+ */
+#[derive(Debug, Clone)]
+enum OpCode {
+    Push(Number),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Neg,
+}
+
+/***
+ * Expr の木を後行順（オペランド→演算子）にたどって命令列へ降ろす。
+ * 2項演算は左・右の順でオペランドを積んでから演算子を並べるので、
+ * VMは命令列を先頭から1回なめるだけで済む。
+ */
+fn compile(expr: Expr, out: &mut Vec<OpCode>) {
+    match expr {
+        Expr::Number(num) => out.push(OpCode::Push(num.unwrap())),
+        Expr::Operation(op) => {
+            let BinaryOp { l, r, op } = *op;
+            compile(l, out);
+            compile(r, out);
+            out.push(match op {
+                OperatorKind::Add => OpCode::Add,
+                OperatorKind::Sub => OpCode::Sub,
+                OperatorKind::Mul => OpCode::Mul,
+                OperatorKind::Div => OpCode::Div,
+                OperatorKind::BitAnd => OpCode::BitAnd,
+                OperatorKind::BitOr => OpCode::BitOr,
+                OperatorKind::BitXor => OpCode::BitXor,
+                OperatorKind::Shl => OpCode::Shl,
+                OperatorKind::Shr => OpCode::Shr,
+                OperatorKind::Eq => OpCode::Eq,
+                OperatorKind::Ne => OpCode::Ne,
+                OperatorKind::Lt => OpCode::Lt,
+                OperatorKind::Le => OpCode::Le,
+                OperatorKind::Gt => OpCode::Gt,
+                OperatorKind::Ge => OpCode::Ge,
+            });
+        }
+        Expr::Unary { op, operand } => {
+            compile(*operand, out);
+            out.push(match op {
+                UnaryKind::Neg => OpCode::Neg,
+            });
+        }
+    }
+}
+
+/***
+ * 算術スタックを持ち、命令列を順に畳んでいく小さなスタックVM
+ */
+struct Vm {
+    stack: Vec<Value>,
+}
+impl Vm {
+    fn new() -> Self {
+        Vm { stack: Vec::new() }
+    }
+    // 数値を要求する文脈でスタックから1個取り出す。
+    fn pop_number(&mut self) -> Result<Number, ArithmeticError> {
+        self.stack.pop().unwrap().into_number()
+    }
+    fn run(&mut self, code: &[OpCode]) -> Result<Value, ArithmeticError> {
+        for op in code {
+            match op {
+                OpCode::Push(n) => self.stack.push(Value::Number(*n)),
+                OpCode::Neg => {
+                    let operand = self.pop_number()?;
+                    self.stack.push(number_result(operand.checked_neg()?));
+                }
+                // 比較演算は数値2つから真偽値を生む。
+                OpCode::Eq
+                | OpCode::Ne
+                | OpCode::Lt
+                | OpCode::Le
+                | OpCode::Gt
+                | OpCode::Ge => {
+                    let r = self.pop_number()?;
+                    let l = self.pop_number()?;
+                    let ord = l.cmp_with(&r);
+                    let b = match op {
+                        OpCode::Eq => ord == Some(std::cmp::Ordering::Equal),
+                        OpCode::Ne => ord != Some(std::cmp::Ordering::Equal),
+                        OpCode::Lt => ord == Some(std::cmp::Ordering::Less),
+                        OpCode::Le => {
+                            matches!(ord, Some(std::cmp::Ordering::Less | std::cmp::Ordering::Equal))
+                        }
+                        OpCode::Gt => ord == Some(std::cmp::Ordering::Greater),
+                        OpCode::Ge => matches!(
+                            ord,
+                            Some(std::cmp::Ordering::Greater | std::cmp::Ordering::Equal)
+                        ),
+                        _ => unreachable!(),
+                    };
+                    self.stack.push(Value::Bool(b));
+                }
+                // 残りは数値同士の算術・ビット演算。
+                _ => {
+                    let r = self.pop_number()?;
+                    let l = self.pop_number()?;
+                    let result = match op {
+                        OpCode::Add => l.checked_add(r)?,
+                        OpCode::Sub => l.checked_sub(r)?,
+                        OpCode::Mul => l.checked_mul(r)?,
+                        OpCode::Div => l.checked_div(r)?,
+                        OpCode::BitAnd => l & r,
+                        OpCode::BitOr => l | r,
+                        OpCode::BitXor => l ^ r,
+                        OpCode::Shl => l.checked_shl(r)?,
+                        OpCode::Shr => l.checked_shr(r)?,
+                        _ => unreachable!(),
+                    };
+                    self.stack.push(number_result(result));
+                }
+            }
+        }
+        // 正しい命令列なら結果が1個だけ残っている。
+        Ok(self.stack.pop().unwrap())
+    }
+}
+
+// Number の演算子が返す Expr を数値 Value へ包み直す小さなヘルパ。
+fn number_result(expr: Expr) -> Value {
+    Value::Number(Number::from_expr(expr).unwrap())
+}
+
+/***
+ * Ok(Value):計算結果（数値または真偽値）
+ * Err(ArithmeticError):解無し(0除算など数学的に解が出ないもの)や型エラー
+ * Expr を命令列へコンパイルし、スタックVMで実行する。
+ */
+fn parser(op: Expr) -> Result<Value, ArithmeticError> {
+    let mut code = Vec::new();
+    compile(op, &mut code);
+    Vm::new().run(&code)
+}
+
+/***
+ * 字句解析が生成するトークン
+ */
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(u128),
+    Float(f64),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    LParen,
+    RParen,
+    Amp,
+    Pipe,
+    Caret,
+    Shl,
+    Shr,
+    EqEq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/***
+ * 構文解析エラーを表現する列挙型
+ * UnbalancedParen: 括弧の対応が取れていない
+ * TrailingTokens: 式を読み終えた後に余計なトークンが残っている
+ * UnexpectedEof: 数値か "(" が来るべき位置でトークンが尽きた、または別のトークンが現れた
+ * UnexpectedChar: 字句解析で解釈できない文字が現れた
+ * NumberOutOfRange: 数値リテラルが i128 の範囲に収まらない
+ */
+enum ParseErrorKind {
+    UnbalancedParen,
+    TrailingTokens,
+    UnexpectedEof,
+    UnexpectedChar,
+    NumberOutOfRange,
+}
+
+#[derive(Debug)]
+struct ParseError {
+    e_code: usize,
+}
+impl ParseError {
+    fn new(code: ParseErrorKind) -> Self {
+        ParseError {
+            e_code: code as usize,
+        }
+    }
+    fn to_enum(&self) -> Option<ParseErrorKind> {
+        match self.e_code {
+            x if x == ParseErrorKind::UnbalancedParen as usize => {
+                Some(ParseErrorKind::UnbalancedParen)
+            }
+            x if x == ParseErrorKind::TrailingTokens as usize => {
+                Some(ParseErrorKind::TrailingTokens)
+            }
+            x if x == ParseErrorKind::UnexpectedEof as usize => Some(ParseErrorKind::UnexpectedEof),
+            x if x == ParseErrorKind::UnexpectedChar as usize => {
+                Some(ParseErrorKind::UnexpectedChar)
+            }
+            x if x == ParseErrorKind::NumberOutOfRange as usize => {
+                Some(ParseErrorKind::NumberOutOfRange)
+            }
+            _ => None,
+        }
+    }
+    fn resolve_string(&self) -> String {
+        match self.to_enum() {
+            Some(ParseErrorKind::UnbalancedParen) => "構文エラー：括弧の対応が取れていません。",
+            Some(ParseErrorKind::TrailingTokens) => "構文エラー：余計なトークンが残っています。",
+            Some(ParseErrorKind::UnexpectedEof) => "構文エラー：式の途中で入力が終了したか、予期しないトークンがあります。",
+            Some(ParseErrorKind::UnexpectedChar) => "構文エラー：解釈できない文字が含まれています。",
+            Some(ParseErrorKind::NumberOutOfRange) => {
+                "構文エラー：数値リテラルが表現可能な範囲を超えています。"
+            }
+            None => "存在しないエラーコードが指定されました。",
+        }
+        .to_owned()
+    }
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.resolve_string())
+    }
+}
+impl std::error::Error for ParseError {}
+
+/***
+ * 文字列を走査してトークン列へ変換する字句解析器
+ */
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' | '\r' => i += 1,
+            '+' => {
+                tokens.push(Token::Plus);
+                i += 1;
+            }
+            '-' => {
+                tokens.push(Token::Minus);
+                i += 1;
+            }
+            '*' => {
+                tokens.push(Token::Star);
+                i += 1;
+            }
+            '/' => {
+                tokens.push(Token::Slash);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '&' => {
+                tokens.push(Token::Amp);
+                i += 1;
+            }
+            '|' => {
+                tokens.push(Token::Pipe);
+                i += 1;
+            }
+            '^' => {
+                tokens.push(Token::Caret);
+                i += 1;
+            }
+            '<' => {
+                if i + 1 < chars.len() && chars[i + 1] == '<' {
+                    tokens.push(Token::Shl);
+                    i += 2;
+                } else if i + 1 < chars.len() && chars[i + 1] == '=' {
+                    tokens.push(Token::Le);
+                    i += 2;
+                } else {
+                    tokens.push(Token::Lt);
+                    i += 1;
                 }
             }
-            Expr::Operation(op) => {
-                // 式を分解して、逆ポーランド記法的な雰囲気でスタックに突っ込んでいく。
-                rev_polish.push(Expr::Operator(op.op));
-                parser_stack.push(op.l);
-                parser_stack.push(op.r);
-            }
-            _ => panic!(), //ここには絶対に来ない。来たら死ぬ。
-        }
-    }
-    // スタックに逆ポーランド的に構成されているので粛々と計算する。
-    // 回答用スタックを用意する
-    let mut ans = Vec::new();
-    loop {
-        match rev_polish.pop().unwrap() {
-            // 何かしら数値が入っていれば、途中経過の計算結果として扱う。
-            Expr::Number(num) => {
-                ans.push(num);
-                // 逆ポーランド記法スタックが空、かつ回答用スタックに1個であれば計算終了とする
-                if rev_polish.len() == 0 && ans.len() == 1 {
-                    break;
+            '>' => {
+                if i + 1 < chars.len() && chars[i + 1] == '>' {
+                    tokens.push(Token::Shr);
+                    i += 2;
+                } else if i + 1 < chars.len() && chars[i + 1] == '=' {
+                    tokens.push(Token::Ge);
+                    i += 2;
+                } else {
+                    tokens.push(Token::Gt);
+                    i += 1;
                 }
             }
-            // 途中経過の計算結果から2個回答を取り出して計算を行う。
-            Expr::Operator(op) => {
-                let r = ans.pop().unwrap().unwrap();
-                let l = ans.pop().unwrap().unwrap();
-                let ans = match op {
-                    OperatorKind::Add => l + r,
-                    OperatorKind::Sub => l - r,
-                    OperatorKind::Mul => l * r,
-                    OperatorKind::Div => {
-                        if r != 0 {
-                            l / r
-                        } else {
-                            return Err(ArithmeticError::new(ArithmeticErrorKind::DivByZero));
-                        }
+            '=' => match chars.get(i + 1) {
+                Some('=') => {
+                    tokens.push(Token::EqEq);
+                    i += 2;
+                }
+                _ => return Err(ParseError::new(ParseErrorKind::UnexpectedChar)),
+            },
+            '!' => match chars.get(i + 1) {
+                Some('=') => {
+                    tokens.push(Token::NotEq);
+                    i += 2;
+                }
+                _ => return Err(ParseError::new(ParseErrorKind::UnexpectedChar)),
+            },
+            '0'..='9' => {
+                let (token, next) = lex_number(&chars, i)?;
+                tokens.push(token);
+                i = next;
+            }
+            _ => return Err(ParseError::new(ParseErrorKind::UnexpectedChar)),
+        }
+    }
+    Ok(tokens)
+}
+
+/***
+ * 数値リテラルを走査し、トークンと走査し終えた次の位置を返す。
+ * 0x/0b/0o プレフィックスは 16/2/8 進数の整数、小数点や指数を含むものは
+ * 浮動小数点リテラルとして扱う。
+ */
+fn lex_number(chars: &[char], start: usize) -> Result<(Token, usize), ParseError> {
+    // プレフィックス付き（0x/0b/0o）は常に整数リテラル。
+    if chars[start] == '0' && start + 1 < chars.len() {
+        let radix = match chars[start + 1] {
+            'x' | 'X' => Some(16u32),
+            'b' | 'B' => Some(2),
+            'o' | 'O' => Some(8),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            let mut i = start + 2;
+            let mut value: u128 = 0;
+            let mut saw_digit = false;
+            while i < chars.len() {
+                match chars[i].to_digit(radix) {
+                    Some(d) => {
+                        // 桁あふれは10進の parse::<u128>() と同様に範囲外エラーにする。
+                        value = value
+                            .checked_mul(radix as u128)
+                            .and_then(|v| v.checked_add(d as u128))
+                            .ok_or_else(|| {
+                                ParseError::new(ParseErrorKind::NumberOutOfRange)
+                            })?;
+                        saw_digit = true;
+                        i += 1;
                     }
-                };
-                rev_polish.push(ans);
+                    None => break,
+                }
+            }
+            // プレフィックスだけで数字が続かない（例: "0x"）のは不正。
+            if !saw_digit {
+                return Err(ParseError::new(ParseErrorKind::UnexpectedChar));
+            }
+            return Ok((Token::Number(value), i));
+        }
+    }
+    // 10進リテラル。小数点・指数があれば浮動小数点として読む。
+    let mut i = start;
+    let mut is_float = false;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    if i < chars.len() && chars[i] == '.' {
+        is_float = true;
+        i += 1;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+        is_float = true;
+        i += 1;
+        if i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+            i += 1;
+        }
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+    }
+    let text: String = chars[start..i].iter().collect();
+    if is_float {
+        let f = text
+            .parse::<f64>()
+            .map_err(|_| ParseError::new(ParseErrorKind::UnexpectedChar))?;
+        Ok((Token::Float(f), i))
+    } else {
+        let value = text
+            .parse::<u128>()
+            .map_err(|_| ParseError::new(ParseErrorKind::NumberOutOfRange))?;
+        Ok((Token::Number(value), i))
+    }
+}
+
+/***
+ * トークン列を再帰下降でたどり Expr の木を組み立てる構文解析器
+ * 文法（比較はビット演算より、ビット演算は算術より弱く結合する）:
+ *   compare = bitwise (("=="|"!="|"<"|"<="|">"|">=") bitwise)?
+ *   bitwise = expr (("&"|"|"|"^"|"<<"|">>") expr)*
+ *   expr    = mul (("+"|"-") mul)*
+ *   mul     = unary (("*"|"/") unary)*
+ *   unary   = ("+"|"-")? primary
+ *   primary = number | "(" compare ")"
+ */
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+impl Parser {
+    fn new(tokens: Vec<Token>) -> Self {
+        Parser { tokens, pos: 0 }
+    }
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn bump(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        if t.is_some() {
+            self.pos += 1;
+        }
+        t
+    }
+    // compare = bitwise (cmp bitwise)? : 真偽値を生む最上位。比較は連鎖させない。
+    fn compare(&mut self) -> Result<Expr, ParseError> {
+        let node = self.bitwise()?;
+        let op = match self.peek() {
+            Some(Token::EqEq) => OperatorKind::Eq,
+            Some(Token::NotEq) => OperatorKind::Ne,
+            Some(Token::Lt) => OperatorKind::Lt,
+            Some(Token::Le) => OperatorKind::Le,
+            Some(Token::Gt) => OperatorKind::Gt,
+            Some(Token::Ge) => OperatorKind::Ge,
+            _ => return Ok(node),
+        };
+        self.bump();
+        let rhs = self.bitwise()?;
+        Ok(BinaryOp::new(node, rhs, op))
+    }
+    // bitwise = expr (("&"|"|"|"^"|"<<"|">>") expr)* : 算術式より弱く結合する。
+    fn bitwise(&mut self) -> Result<Expr, ParseError> {
+        let mut node = self.expr()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Amp) => OperatorKind::BitAnd,
+                Some(Token::Pipe) => OperatorKind::BitOr,
+                Some(Token::Caret) => OperatorKind::BitXor,
+                Some(Token::Shl) => OperatorKind::Shl,
+                Some(Token::Shr) => OperatorKind::Shr,
+                _ => break,
+            };
+            self.bump();
+            let rhs = self.expr()?;
+            node = BinaryOp::new(node, rhs, op);
+        }
+        Ok(node)
+    }
+    // expr = mul (("+"|"-") mul)* : +/- は左結合で走っている木へ畳み込む。
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut node = self.mul()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => OperatorKind::Add,
+                Some(Token::Minus) => OperatorKind::Sub,
+                _ => break,
+            };
+            self.bump();
+            let rhs = self.mul()?;
+            node = BinaryOp::new(node, rhs, op);
+        }
+        Ok(node)
+    }
+    // mul = unary (("*"|"/") unary)* : 乗除は加減より強く結合する。
+    fn mul(&mut self) -> Result<Expr, ParseError> {
+        let mut node = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => OperatorKind::Mul,
+                Some(Token::Slash) => OperatorKind::Div,
+                _ => break,
+            };
+            self.bump();
+            let rhs = self.unary()?;
+            node = BinaryOp::new(node, rhs, op);
+        }
+        Ok(node)
+    }
+    // unary = ("+"|"-")? primary : 先頭の符号を畳み込む。
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        match self.peek() {
+            Some(Token::Plus) => {
+                self.bump();
+                self.primary()
             }
-            _ => panic!(), // ここには来ない。
+            Some(Token::Minus) => {
+                self.bump();
+                let operand = self.primary()?;
+                Ok(Expr::Unary {
+                    op: UnaryKind::Neg,
+                    operand: Box::new(operand),
+                })
+            }
+            _ => self.primary(),
+        }
+    }
+    // primary = number | "(" compare ")"
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        match self.bump() {
+            Some(Token::Number(n)) => {
+                // u128 の整数リテラルが i128 に収まらなければ拒否する（暗黙の負数化を防ぐ）。
+                if n > i128::MAX as u128 {
+                    return Err(ParseError::new(ParseErrorKind::NumberOutOfRange));
+                }
+                Ok(Number::new(n as i128))
+            }
+            Some(Token::Float(f)) => Ok(Number::floating(f)),
+            Some(Token::LParen) => {
+                let node = self.compare()?;
+                match self.bump() {
+                    Some(Token::RParen) => Ok(node),
+                    _ => Err(ParseError::new(ParseErrorKind::UnbalancedParen)),
+                }
+            }
+            _ => Err(ParseError::new(ParseErrorKind::UnexpectedEof)),
         }
     }
-    // スタックが0で取得できないことはありえない（解無し）という前提がある。
-    // さらに、解なしの場合はOption<Number>もunwrap出来ないので正常にリターンできないはず。
-    // むしろそれが起きたらpanicするのが正しいのでunwrapの実装でOK。
-    Ok(ans.pop().unwrap().unwrap())
+}
+
+/***
+ * 文字列を字句解析・構文解析して Expr の木を返す。
+ * parser() はこの前段が組み立てた木を評価する段として残す。
+ */
+fn parse(input: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(tokenize(input)?);
+    let expr = parser.compare()?;
+    if parser.pos != parser.tokens.len() {
+        return Err(ParseError::new(ParseErrorKind::TrailingTokens));
+    }
+    Ok(expr)
 }
 
 #[test]
@@ -243,3 +922,189 @@ fn parser_test5() {
     let d = BinaryOp::new(c.clone(), c, OperatorKind::Add);
     assert_eq!(parser(d.clone()).unwrap(), 48);
 }
+
+#[test]
+fn parse_test1() {
+    // 5+6*7 = 47（乗算が加算より強く結合する）
+    let expr = parse("5+6*7").unwrap();
+    assert_eq!(parser(expr).unwrap(), 47);
+}
+
+#[test]
+fn parse_test2() {
+    // 5*(9-6) = 15（括弧が優先される）
+    let expr = parse("5*(9-6)").unwrap();
+    assert_eq!(parser(expr).unwrap(), 15);
+}
+
+#[test]
+fn parse_test3() {
+    // 20-5-3 = 12（減算は左結合）
+    let expr = parse("20-5-3").unwrap();
+    assert_eq!(parser(expr).unwrap(), 12);
+}
+
+#[test]
+fn parse_test_unbalanced() {
+    // 閉じ括弧が足りない
+    assert!(parse("5*(9-6").is_err());
+}
+
+#[test]
+fn parse_test_hex() {
+    // 0xff = 255
+    let expr = parse("0xff").unwrap();
+    assert_eq!(parser(expr).unwrap(), 255);
+}
+
+#[test]
+fn parse_rejects_out_of_range_hex_literal() {
+    // u128 に収まらない16進リテラルは panic せず範囲外エラーになる
+    assert!(parse("0xffffffffffffffffffffffffffffffffff").is_err());
+}
+
+#[test]
+fn parse_test_radix_mix() {
+    // 0b1010 | 0o5 = 10 | 5 = 15
+    let expr = parse("0b1010|0o5").unwrap();
+    assert_eq!(parser(expr).unwrap(), 15);
+}
+
+#[test]
+fn parse_test_bitwise_precedence() {
+    // 1+1 << 2 = (1+1) << 2 = 8（算術がシフトより強い）
+    let expr = parse("1+1<<2").unwrap();
+    assert_eq!(parser(expr).unwrap(), 8);
+}
+
+#[test]
+fn parse_test_negative_result() {
+    // 14 - 20 = -6（符号付きなのでアンダーフローしない）
+    let expr = parse("14-20").unwrap();
+    assert_eq!(parser(expr).unwrap(), -6);
+}
+
+#[test]
+fn parse_test_unary_minus() {
+    // 12 + (-7) = 5
+    let expr = parse("12+(-7)").unwrap();
+    assert_eq!(parser(expr).unwrap(), 5);
+}
+
+#[test]
+fn parse_test_unary_in_mul() {
+    // 20 + (-3*5) = 5
+    let expr = parse("20+(-3*5)").unwrap();
+    assert_eq!(parser(expr).unwrap(), 5);
+}
+
+#[test]
+fn parser_neg_overflow_returns_error() {
+    // i128::MIN の符号反転は panic せずオーバーフローエラーになる
+    let a = Expr::Unary {
+        op: UnaryKind::Neg,
+        operand: Box::new(Number::new(i128::MIN)),
+    };
+    assert!(parser(a).is_err());
+}
+
+#[test]
+fn parse_rejects_out_of_range_literal() {
+    // i128::MAX を超える10進リテラルは暗黙に負数化せず拒否する
+    assert!(parse("170141183460469231731687303715884105728").is_err());
+}
+
+#[test]
+fn parse_test_compare_true() {
+    // 5 > 3 = true
+    let expr = parse("5>3").unwrap();
+    assert!(matches!(parser(expr).unwrap(), Value::Bool(true)));
+}
+
+#[test]
+fn parse_test_compare_false() {
+    // 2+2 == 5 は false（比較は算術より弱く結合する）
+    let expr = parse("2+2==5").unwrap();
+    assert!(matches!(parser(expr).unwrap(), Value::Bool(false)));
+}
+
+#[test]
+fn parse_test_compare_integer_precision() {
+    // f64 のマンティッサを超える2つの整数は等しく扱わない
+    let expr = parse("9007199254740992==9007199254740993").unwrap();
+    assert!(matches!(parser(expr).unwrap(), Value::Bool(false)));
+}
+
+#[test]
+fn parse_test_compare_type_error() {
+    // 真偽値を算術に渡すと型エラーになる
+    let expr = parse("(1<2)+3").unwrap();
+    assert!(parser(expr).is_err());
+}
+
+#[test]
+fn vm_reuse_program() {
+    // 一度コンパイルした命令列は何度でも実行できる
+    let mut code = Vec::new();
+    compile(parse("2*3+4").unwrap(), &mut code);
+    assert_eq!(Vm::new().run(&code).unwrap(), 10);
+    assert_eq!(Vm::new().run(&code).unwrap(), 10);
+}
+
+#[test]
+fn parse_test_integer_division_exact() {
+    // 4 + 8 / 2 = 8（割り切れるので整数のまま）
+    let expr = parse("4+8/2").unwrap();
+    assert_eq!(parser(expr).unwrap(), 8);
+}
+
+#[test]
+fn parse_test_division_promotes_to_float() {
+    // 5 / 2 = 2.5（割り切れないので浮動小数へ昇格する）
+    let expr = parse("5/2").unwrap();
+    let n = parser(expr).unwrap().into_number().unwrap();
+    assert_eq!(n.as_f64(), 2.5);
+}
+
+#[test]
+fn parse_test_float_literal() {
+    // 1.5 + 2 = 3.5（どちらかが浮動小数なら結果も浮動小数）
+    let expr = parse("1.5+2").unwrap();
+    let n = parser(expr).unwrap().into_number().unwrap();
+    assert_eq!(n.as_f64(), 3.5);
+}
+
+#[test]
+fn parser_overflow_returns_error() {
+    // i128::MAX + 1 は panic せずオーバーフローエラーになる
+    let a = BinaryOp::new(Number::new(i128::MAX), Number::new(1), OperatorKind::Add);
+    assert!(parser(a).is_err());
+}
+
+#[test]
+fn parser_underflow_returns_error() {
+    // i128::MIN - 1 は panic せずアンダーフローエラーになる
+    let a = BinaryOp::new(Number::new(i128::MIN), Number::new(1), OperatorKind::Sub);
+    assert!(parser(a).is_err());
+}
+
+#[test]
+fn parser_div_overflow_returns_error() {
+    // (1<<127)/(0-1) すなわち i128::MIN / -1 は panic せずオーバーフローエラーになる
+    let expr = parse("(1<<127)/(0-1)").unwrap();
+    assert!(parser(expr).is_err());
+}
+
+#[test]
+fn parser_shift_amount_too_large_returns_error() {
+    // 1 << 200 は panic せずエラーになる（シフト量が桁数以上）
+    let expr = parse("1<<200").unwrap();
+    assert!(parser(expr).is_err());
+}
+
+#[test]
+fn parser_negative_shift_returns_error() {
+    // 4 >> (0-1) は panic せずエラーになる（負のシフト量）
+    let expr = parse("4>>(0-1)").unwrap();
+    assert!(parser(expr).is_err());
+}